@@ -1,6 +1,7 @@
 //! All things related to errors and error contexts.
 
 use super::{AppInstruction, OPENCALLS};
+use crate::common::input::actions::TerminalAction;
 use crate::pty_bus::PtyInstruction;
 use crate::screen::ScreenInstruction;
 
@@ -8,68 +9,246 @@ use std::fmt::{Display, Error, Formatter};
 
 const MAX_THREAD_CALL_STACK: usize = 6;
 
-#[cfg(not(test))]
+thread_local! {
+    /// Mirrors [`OPENCALLS`], but for the call stack captured by async tasks (the
+    /// `AsyncTask`/`stream_terminal_bytes` path) so a future running on the same thread
+    /// as a synchronous instruction handler doesn't clobber that handler's context.
+    static ASYNCOPENCALLS: std::cell::RefCell<ErrorContext> = std::cell::RefCell::new(ErrorContext::new());
+}
+
+/// Returns the call stack that should be reported for an error happening right now on
+/// this thread: the async-task stack in [`ASYNCOPENCALLS`] if one has been recorded,
+/// since that reflects the future actually running, otherwise the synchronous
+/// [`OPENCALLS`] stack.
+fn current_context() -> ErrorContext {
+    let async_ctx = ASYNCOPENCALLS.with(|ctx| *ctx.borrow());
+    if async_ctx.calls[0] != ContextType::Empty {
+        async_ctx
+    } else {
+        OPENCALLS.with(|ctx| *ctx.borrow())
+    }
+}
+
+/// Clears this thread's [`ASYNCOPENCALLS`] stack, so [`current_context`] falls back to
+/// the synchronous [`OPENCALLS`] stack again. Call once the async task that last called
+/// [`ErrorContext::add_call_async`] on this thread finishes; otherwise that task's stack
+/// is preferred forever, even for errors from sync handlers that run afterwards.
+pub fn clear_async_context() {
+    ASYNCOPENCALLS.with(|ctx| *ctx.borrow_mut() = ErrorContext::new());
+}
+
+/// Crate-wide error handling prelude.
+///
+/// Instruction handlers should `use crate::common::errors::prelude::*;` and propagate
+/// recoverable errors with `anyhow::Result`/`Context` rather than `.unwrap()`-ing,
+/// attaching a human-readable frame on top of the [`ErrorContext`] call stack, e.g.
+/// `.with_context(|| format!("failed to handle {:?}", ctx))?`.
+pub mod prelude {
+    pub use super::{FatalError, LoggableError};
+    pub use anyhow::Context;
+}
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+
 use super::SenderWithContext;
+
+thread_local! {
+    /// The channel `fatal`/`unwrap_or_exit` send an [`AppInstruction::Error`] down, set
+    /// once per thread via [`set_fatal_sender`]. `None` on the main thread, which exits
+    /// directly instead.
+    static FATAL_SENDER: RefCell<Option<SenderWithContext<AppInstruction>>> = const { RefCell::new(None) };
+}
+
+/// Registers the channel this thread should use to report fatal errors back to the
+/// main thread. Call once at thread startup, mirroring how [`OPENCALLS`] is seeded.
+pub fn set_fatal_sender(sender: SenderWithContext<AppInstruction>) {
+    FATAL_SENDER.with(|s| *s.borrow_mut() = Some(sender));
+}
+
+/// Extension methods for logging a recoverable [`anyhow::Result`] without unwinding.
+pub trait LoggableError<T> {
+    /// Logs the error chain (including the current [`ErrorContext`]) to stderr and
+    /// returns the original `Result` unchanged, so it can still be chained with `?`.
+    fn print_error(self, f: &str) -> Self;
+    /// Convenience alias for [`LoggableError::print_error`] for call sites that don't
+    /// have a more specific label to attach.
+    fn non_fatal(self) -> Self;
+}
+
+impl<T> LoggableError<T> for Result<T> {
+    fn print_error(self, f: &str) -> Self {
+        if let Err(ref err) = self {
+            let err_ctx = current_context();
+            eprintln!("{}\nError while {}: {:?}", err_ctx, f, err);
+        }
+        self
+    }
+
+    fn non_fatal(self) -> Self {
+        self.print_error("handling an instruction")
+    }
+}
+
+/// Extension methods for treating an [`anyhow::Result`] as unrecoverable.
+pub trait FatalError<T> {
+    /// On `Err`, renders the full context-annotated chain and either sends an
+    /// [`AppInstruction::Error`] or exits, mirroring the split logic in [`handle_panic`].
+    /// Returns the unwrapped value on `Ok`.
+    fn fatal(self) -> T;
+    /// Like [`FatalError::fatal`], but attaches `ctx` as the top frame of the error
+    /// chain before rendering, for call sites that can't use `with_context` inline.
+    fn unwrap_or_exit(self, ctx: &str) -> T;
+}
+
+impl<T> FatalError<T> for Result<T> {
+    fn fatal(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(err) => {
+                let err_ctx = current_context();
+                let backtrace = format!("{}\nFatal error: {:?}", err_ctx, err);
+                exit_or_report(backtrace)
+            }
+        }
+    }
+
+    fn unwrap_or_exit(self, ctx: &str) -> T {
+        self.with_context(|| ctx.to_string()).fatal()
+    }
+}
+
 #[cfg(not(test))]
-use std::panic::PanicInfo;
-#[cfg(not(test))]
+fn exit_or_report(backtrace: String) -> ! {
+    use std::process;
+    let sent = FATAL_SENDER.with(|sender| {
+        sender
+            .borrow()
+            .as_ref()
+            .map(|sender| sender.send(AppInstruction::Error(backtrace.clone())).unwrap())
+    });
+    match sent {
+        Some(()) => loop {
+            std::thread::park();
+        },
+        None => {
+            println!("{}", backtrace);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+fn exit_or_report(backtrace: String) -> ! {
+    panic!("{}", backtrace);
+}
+
+use std::panic::PanicHookInfo;
+
+/// A [`miette::Diagnostic`] rendering of a panic, with the originating thread's
+/// [`ErrorContext`] call stack attached as `related` diagnostics so the report shows
+/// which instructions led to the crash, not just where it happened.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("thread '{thread}' panicked at '{message}'")]
+pub struct Panic {
+    message: String,
+    #[help]
+    location: Option<String>,
+    thread: String,
+    #[source]
+    trace: PanicBacktrace,
+    #[related]
+    related: Vec<PanicFrame>,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{0}")]
+struct PanicBacktrace(String);
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{0}")]
+struct PanicFrame(String);
+
+/// Renders `err` as a `miette` graphical report, the same style used for panics.
+pub fn fmt_error(err: &anyhow::Error) -> String {
+    let diag = miette::miette!("{:?}", err);
+    format!("{:?}", diag)
+}
+
+/// Installs [`handle_panic`] as the global panic hook, so panics render as `miette`
+/// diagnostics (with the call-stack frames from [`ErrorContext`] as related context)
+/// instead of the manual ANSI-escaped backtrace dump this module used to build by hand.
+/// Call once at process startup with the sender [`handle_panic`] should use to forward
+/// background-thread panics to the app thread.
+pub fn install_miette_panic_hook(send_app_instructions: SenderWithContext<AppInstruction>) {
+    std::panic::set_hook(Box::new(move |info| {
+        handle_panic(info, &send_app_instructions);
+    }));
+}
+
+fn build_panic(info: &PanicHookInfo<'_>, err_ctx: ErrorContext) -> Panic {
+    use backtrace::Backtrace;
+    use std::thread;
+
+    let thread = thread::current();
+    let thread = thread.name().unwrap_or("unnamed").to_string();
+
+    let message = match info.payload().downcast_ref::<&'static str>() {
+        Some(s) => s.to_string(),
+        None => info
+            .payload()
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    };
+
+    let location = info
+        .location()
+        .map(|l| format!("{}:{}", l.file(), l.line()));
+
+    let related = err_ctx
+        .calls
+        .iter()
+        .take_while(|c| **c != ContextType::Empty)
+        .map(|c| PanicFrame(format!("{}", c)))
+        .collect();
+
+    Panic {
+        message,
+        location,
+        thread,
+        trace: PanicBacktrace(format!("{:?}", Backtrace::new())),
+        related,
+    }
+}
+
+/// Renders a panic and dispatches it: on the main thread it's printed and the process
+/// exits, otherwise it's forwarded as an [`AppInstruction::Error`] for the app thread to
+/// surface, mirroring the split logic in [`FatalError::fatal`].
 pub fn handle_panic(
-    info: &PanicInfo<'_>,
+    info: &PanicHookInfo<'_>,
     send_app_instructions: &SenderWithContext<AppInstruction>,
 ) {
-    use backtrace::Backtrace;
     use std::{process, thread};
-    let backtrace = Backtrace::new();
+
+    let err_ctx = current_context();
+    let panic = build_panic(info, err_ctx);
     let thread = thread::current();
     let thread = thread.name().unwrap_or("unnamed");
-
-    let msg = match info.payload().downcast_ref::<&'static str>() {
-        Some(s) => Some(*s),
-        None => info.payload().downcast_ref::<String>().map(|s| &**s),
-    };
-
-    let err_ctx = OPENCALLS.with(|ctx| *ctx.borrow());
-
-    let backtrace = match (info.location(), msg) {
-        (Some(location), Some(msg)) => format!(
-            "{}\n\u{1b}[0;0mError: \u{1b}[0;31mthread '{}' panicked at '{}': {}:{}\n\u{1b}[0;0m{:?}",
-            err_ctx,
-            thread,
-            msg,
-            location.file(),
-            location.line(),
-            backtrace
-        ),
-        (Some(location), None) => format!(
-            "{}\n\u{1b}[0;0mError: \u{1b}[0;31mthread '{}' panicked: {}:{}\n\u{1b}[0;0m{:?}",
-            err_ctx,
-            thread,
-            location.file(),
-            location.line(),
-            backtrace
-        ),
-        (None, Some(msg)) => format!(
-            "{}\n\u{1b}[0;0mError: \u{1b}[0;31mthread '{}' panicked at '{}'\n\u{1b}[0;0m{:?}",
-            err_ctx, thread, msg, backtrace
-        ),
-        (None, None) => format!(
-            "{}\n\u{1b}[0;0mError: \u{1b}[0;31mthread '{}' panicked\n\u{1b}[0;0m{:?}",
-            err_ctx, thread, backtrace
-        ),
-    };
+    let report = format!("{:?}", miette::Report::new(panic));
 
     if thread == "main" {
-        println!("{}", backtrace);
+        println!("{}", report);
         process::exit(1);
     } else {
         send_app_instructions
-            .send(AppInstruction::Error(backtrace))
+            .send(AppInstruction::Error(report))
             .unwrap();
     }
 }
 
 /// An [`ErrorContext`] struct contains a representation of the call stack
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub struct ErrorContext {
     calls: [ContextType; MAX_THREAD_CALL_STACK],
 }
@@ -90,6 +269,20 @@ impl ErrorContext {
         }
         OPENCALLS.with(|ctx| *ctx.borrow_mut() = *self);
     }
+
+    /// Like [`ErrorContext::add_call`], but records into the current thread's
+    /// [`ASYNCOPENCALLS`] instead of [`OPENCALLS`]. Async tasks spawned on a thread that
+    /// also runs a synchronous instruction handler (e.g. `stream_terminal_bytes`) would
+    /// otherwise clobber that handler's call stack since both live on the same thread.
+    pub fn add_call_async(&mut self, call: ContextType) {
+        for ctx in self.calls.iter_mut() {
+            if *ctx == ContextType::Empty {
+                *ctx = call;
+                break;
+            }
+        }
+        ASYNCOPENCALLS.with(|ctx| *ctx.borrow_mut() = *self);
+    }
 }
 
 impl Default for ErrorContext {
@@ -116,7 +309,7 @@ impl Display for ErrorContext {
 /// Complex variants store a variant of a related enum, whose variants can be built from
 /// the related custom Zellij MSPC instruction enum variants ([`ScreenInstruction`],
 /// [`PtyInstruction`], [`AppInstruction`], etc.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ContextType {
     Screen(ScreenContext),
     Pty(PtyContext),
@@ -151,150 +344,118 @@ impl Display for ContextType {
     }
 }
 
-/// An element of the error context related to [`ScreenInstruction`]s.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ScreenContext {
-    HandlePtyEvent,
-    Render,
-    NewPane,
-    HorizontalSplit,
-    VerticalSplit,
-    WriteCharacter,
-    ResizeLeft,
-    ResizeRight,
-    ResizeDown,
-    ResizeUp,
-    MoveFocus,
-    MoveFocusLeft,
-    MoveFocusDown,
-    MoveFocusUp,
-    MoveFocusRight,
-    Quit,
-    ScrollUp,
-    ScrollDown,
-    ClearScroll,
-    CloseFocusedPane,
-    ToggleActiveTerminalFullscreen,
-    SetSelectable,
-    SetInvisibleBorders,
-    SetMaxHeight,
-    ClosePane,
-    ApplyLayout,
-    NewTab,
-    SwitchTabNext,
-    SwitchTabPrev,
-    CloseTab,
-}
+/// Generates a `*Context` enum paired to an `Instruction` enum, along with the
+/// `From<&Instruction>` mapping used to build an [`ErrorContext`] frame out of it.
+///
+/// Each arm is `InstructionVariant $(payload_pattern)? => ContextVariant`; the payload
+/// pattern (e.g. `(..)`, `(_)`) is only needed for variants that carry data, since the
+/// context enum itself never stores payloads. This keeps the two enums exhaustively in
+/// sync at compile time: adding an instruction variant without a matching arm here is a
+/// compile error instead of a silently-missing context frame.
+macro_rules! context_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $ctx_name:ident from $instr_name:ident {
+            $($instr_variant:ident $(($($pat:tt)*))? => $ctx_variant:ident),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+        $vis enum $ctx_name {
+            $($ctx_variant),+
+        }
 
-impl From<&ScreenInstruction> for ScreenContext {
-    fn from(screen_instruction: &ScreenInstruction) -> Self {
-        match *screen_instruction {
-            ScreenInstruction::Pty(..) => ScreenContext::HandlePtyEvent,
-            ScreenInstruction::Render => ScreenContext::Render,
-            ScreenInstruction::NewPane(_) => ScreenContext::NewPane,
-            ScreenInstruction::HorizontalSplit(_) => ScreenContext::HorizontalSplit,
-            ScreenInstruction::VerticalSplit(_) => ScreenContext::VerticalSplit,
-            ScreenInstruction::WriteCharacter(_) => ScreenContext::WriteCharacter,
-            ScreenInstruction::ResizeLeft => ScreenContext::ResizeLeft,
-            ScreenInstruction::ResizeRight => ScreenContext::ResizeRight,
-            ScreenInstruction::ResizeDown => ScreenContext::ResizeDown,
-            ScreenInstruction::ResizeUp => ScreenContext::ResizeUp,
-            ScreenInstruction::MoveFocus => ScreenContext::MoveFocus,
-            ScreenInstruction::MoveFocusLeft => ScreenContext::MoveFocusLeft,
-            ScreenInstruction::MoveFocusDown => ScreenContext::MoveFocusDown,
-            ScreenInstruction::MoveFocusUp => ScreenContext::MoveFocusUp,
-            ScreenInstruction::MoveFocusRight => ScreenContext::MoveFocusRight,
-            ScreenInstruction::Quit => ScreenContext::Quit,
-            ScreenInstruction::ScrollUp => ScreenContext::ScrollUp,
-            ScreenInstruction::ScrollDown => ScreenContext::ScrollDown,
-            ScreenInstruction::ClearScroll => ScreenContext::ClearScroll,
-            ScreenInstruction::CloseFocusedPane => ScreenContext::CloseFocusedPane,
-            ScreenInstruction::ToggleActiveTerminalFullscreen => {
-                ScreenContext::ToggleActiveTerminalFullscreen
+        impl From<&$instr_name> for $ctx_name {
+            fn from(instruction: &$instr_name) -> Self {
+                match *instruction {
+                    $($instr_name::$instr_variant $(($($pat)*))? => $ctx_name::$ctx_variant),+
+                }
             }
-            ScreenInstruction::SetSelectable(..) => ScreenContext::SetSelectable,
-            ScreenInstruction::SetInvisibleBorders(..) => ScreenContext::SetInvisibleBorders,
-            ScreenInstruction::SetMaxHeight(..) => ScreenContext::SetMaxHeight,
-            ScreenInstruction::ClosePane(_) => ScreenContext::ClosePane,
-            ScreenInstruction::ApplyLayout(_) => ScreenContext::ApplyLayout,
-            ScreenInstruction::NewTab(_) => ScreenContext::NewTab,
-            ScreenInstruction::SwitchTabNext => ScreenContext::SwitchTabNext,
-            ScreenInstruction::SwitchTabPrev => ScreenContext::SwitchTabPrev,
-            ScreenInstruction::CloseTab => ScreenContext::CloseTab,
         }
-    }
+    };
 }
 
-/// An element of the error context related to [`PtyInstruction`]s.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum PtyContext {
-    SpawnTerminal,
-    SpawnTerminalVertically,
-    SpawnTerminalHorizontally,
-    NewTab,
-    ClosePane,
-    CloseTab,
-    Quit,
+context_enum! {
+    /// An element of the error context related to [`ScreenInstruction`]s.
+    pub enum ScreenContext from ScreenInstruction {
+        Pty(..) => HandlePtyEvent,
+        Render => Render,
+        NewPane(_, TerminalAction::DefaultShell) => NewPane,
+        NewPane(_, TerminalAction::RunCommand(_)) => Run,
+        HorizontalSplit(_) => HorizontalSplit,
+        VerticalSplit(_) => VerticalSplit,
+        WriteCharacter(_) => WriteCharacter,
+        ResizeLeft => ResizeLeft,
+        ResizeRight => ResizeRight,
+        ResizeDown => ResizeDown,
+        ResizeUp => ResizeUp,
+        MoveFocus => MoveFocus,
+        MoveFocusLeft => MoveFocusLeft,
+        MoveFocusDown => MoveFocusDown,
+        MoveFocusUp => MoveFocusUp,
+        MoveFocusRight => MoveFocusRight,
+        Quit => Quit,
+        ScrollUp => ScrollUp,
+        ScrollDown => ScrollDown,
+        ClearScroll => ClearScroll,
+        CloseFocusedPane => CloseFocusedPane,
+        ToggleActiveTerminalFullscreen => ToggleActiveTerminalFullscreen,
+        SetSelectable(..) => SetSelectable,
+        SetInvisibleBorders(..) => SetInvisibleBorders,
+        SetMaxHeight(..) => SetMaxHeight,
+        ClosePane(_) => ClosePane,
+        ApplyLayout(_) => ApplyLayout,
+        NewTab(_) => NewTab,
+        SwitchTabNext => SwitchTabNext,
+        SwitchTabPrev => SwitchTabPrev,
+        CloseTab => CloseTab,
+        Search(_) => Search,
+        SearchDown => SearchDown,
+        SearchUp => SearchUp,
+        ResetSearch => ResetSearch,
+        SearchToggleOption(_) => SearchToggleOption,
+    }
 }
 
-impl From<&PtyInstruction> for PtyContext {
-    fn from(pty_instruction: &PtyInstruction) -> Self {
-        match *pty_instruction {
-            PtyInstruction::SpawnTerminal(_) => PtyContext::SpawnTerminal,
-            PtyInstruction::SpawnTerminalVertically(_) => PtyContext::SpawnTerminalVertically,
-            PtyInstruction::SpawnTerminalHorizontally(_) => PtyContext::SpawnTerminalHorizontally,
-            PtyInstruction::ClosePane(_) => PtyContext::ClosePane,
-            PtyInstruction::CloseTab(_) => PtyContext::CloseTab,
-            PtyInstruction::NewTab => PtyContext::NewTab,
-            PtyInstruction::Quit => PtyContext::Quit,
-        }
+context_enum! {
+    /// An element of the error context related to [`PtyInstruction`]s. The `SpawnTerminal*`
+    /// variants are split by whether they're spawning the default login shell or a
+    /// `TerminalAction::RunCommand`, so an `Action::Run` is distinguishable in the error
+    /// context from a plain `Action::NewPane`.
+    pub enum PtyContext from PtyInstruction {
+        SpawnTerminal(TerminalAction::DefaultShell) => SpawnTerminal,
+        SpawnTerminal(TerminalAction::RunCommand(_)) => RunCommand,
+        SpawnTerminalVertically(TerminalAction::DefaultShell) => SpawnTerminalVertically,
+        SpawnTerminalVertically(TerminalAction::RunCommand(_)) => RunCommandVertically,
+        SpawnTerminalHorizontally(TerminalAction::DefaultShell) => SpawnTerminalHorizontally,
+        SpawnTerminalHorizontally(TerminalAction::RunCommand(_)) => RunCommandHorizontally,
+        NewTab => NewTab,
+        ClosePane(_) => ClosePane,
+        CloseTab(_) => CloseTab,
+        Quit => Quit,
     }
 }
 
-// FIXME: This whole pattern *needs* a macro eventually, it's soul-crushing to write
-
 use crate::wasm_vm::PluginInstruction;
 
-/// An element of the error context related to [`PluginInstruction`]s.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum PluginContext {
-    Load,
-    Draw,
-    Input,
-    GlobalInput,
-    Unload,
-    Quit,
-}
-
-impl From<&PluginInstruction> for PluginContext {
-    fn from(plugin_instruction: &PluginInstruction) -> Self {
-        match *plugin_instruction {
-            PluginInstruction::Load(..) => PluginContext::Load,
-            PluginInstruction::Draw(..) => PluginContext::Draw,
-            PluginInstruction::Input(..) => PluginContext::Input,
-            PluginInstruction::GlobalInput(_) => PluginContext::GlobalInput,
-            PluginInstruction::Unload(_) => PluginContext::Unload,
-            PluginInstruction::Quit => PluginContext::Quit,
-        }
+context_enum! {
+    /// An element of the error context related to [`PluginInstruction`]s.
+    pub enum PluginContext from PluginInstruction {
+        Load(..) => Load,
+        Draw(..) => Draw,
+        Input(..) => Input,
+        GlobalInput(_) => GlobalInput,
+        Unload(_) => Unload,
+        Quit => Quit,
     }
 }
 
-/// An element of the error context related to [`AppInstruction`]s.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum AppContext {
-    GetState,
-    SetState,
-    Exit,
-    Error,
-}
-
-impl From<&AppInstruction> for AppContext {
-    fn from(app_instruction: &AppInstruction) -> Self {
-        match *app_instruction {
-            AppInstruction::GetState(_) => AppContext::GetState,
-            AppInstruction::SetState(_) => AppContext::SetState,
-            AppInstruction::Exit => AppContext::Exit,
-            AppInstruction::Error(_) => AppContext::Error,
-        }
+context_enum! {
+    /// An element of the error context related to [`AppInstruction`]s.
+    pub enum AppContext from AppInstruction {
+        GetState(_) => GetState,
+        SetState(_) => SetState,
+        Exit => Exit,
+        Error(_) => Error,
     }
 }
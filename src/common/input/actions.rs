@@ -2,6 +2,7 @@
 /// response to a keybind and also passing actions back to the handler
 /// for dispatch.
 use super::handler;
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub enum Direction {
@@ -11,6 +12,65 @@ pub enum Direction {
     Down,
 }
 
+/// What a freshly spawned terminal pane should run, passed down to
+/// `PtyInstruction::SpawnTerminal`.
+#[derive(Clone)]
+pub enum TerminalAction {
+    /// Spawn the user's login shell (the default when a pane is opened via `NewPane`).
+    DefaultShell,
+    /// Spawn the given [`RunCommand`] instead of the login shell.
+    RunCommand(RunCommand),
+}
+
+/// A command to run in a freshly spawned pane, as opposed to the default login shell.
+#[derive(Clone)]
+pub struct RunCommand {
+    /// The program to run.
+    pub command: PathBuf,
+    /// Arguments to pass to `command`.
+    pub args: Vec<String>,
+    /// The working directory to spawn `command` in, defaulting to the current one.
+    pub cwd: Option<PathBuf>,
+    /// The name to give the pane, shown in its frame. Defaults to `command` if unset.
+    pub name: Option<String>,
+    /// Which way to split the focus pane to make room for the new one.
+    pub direction: Direction,
+}
+
+impl TerminalAction {
+    /// The name the new pane's frame should show: [`RunCommand::name`] if set, falling
+    /// back to the command itself, or `None` for the default shell.
+    pub fn pane_name(&self) -> Option<String> {
+        match self {
+            TerminalAction::DefaultShell => None,
+            TerminalAction::RunCommand(run_command) => Some(
+                run_command
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| run_command.command.display().to_string()),
+            ),
+        }
+    }
+}
+
+/// The direction to move through scrollback search matches.
+#[derive(Clone)]
+pub enum SearchDirection {
+    Up,
+    Down,
+}
+
+/// A toggleable option affecting how scrollback search matches are found.
+#[derive(Clone)]
+pub enum SearchOption {
+    /// Search case sensitively or not.
+    CaseSensitivity,
+    /// Wrap the search around the start/end of the scrollback.
+    Wrap,
+    /// Only match whole words.
+    WholeWord,
+}
+
 #[derive(Clone)]
 pub enum Action {
     /// Quit Zellij.
@@ -34,6 +94,9 @@ pub enum Action {
     /// Open a new pane in the specified direction (relative to focus).
     /// If no direction is specified, will try to use the biggest available space.
     NewPane(Option<Direction>),
+    /// Run a command in a new pane in the specified direction, instead of the default
+    /// login shell.
+    Run(RunCommand),
     /// Close the focus pane.
     CloseFocus,
     /// Create a new tab.
@@ -44,4 +107,10 @@ pub enum Action {
     GoToPreviousTab,
     /// Close the current tab.
     CloseTab,
+    /// Search for the next/previous match in the focus pane's scrollback.
+    Search(SearchDirection),
+    /// Toggle a search option on or off.
+    SearchToggleOption(SearchOption),
+    /// Append to the current search term.
+    SearchInput(Vec<u8>),
 }
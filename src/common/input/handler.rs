@@ -0,0 +1,11 @@
+//! The input mode: which set of keybindings is currently active.
+
+#[derive(Clone)]
+pub enum InputMode {
+    Normal,
+    Resize,
+    Pane,
+    Tab,
+    Scroll,
+    Search,
+}
@@ -0,0 +1,5 @@
+//! User input: the actions a keybind can produce, and the mode that selects which
+//! bindings are active.
+
+pub mod actions;
+pub mod handler;
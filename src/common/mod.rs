@@ -0,0 +1,94 @@
+//! Infrastructure shared across threads: the app-level instruction enum and the
+//! channel wrapper that carries an [`errors::ErrorContext`] alongside every message.
+
+pub mod errors;
+pub mod input;
+
+use errors::ErrorContext;
+use std::cell::RefCell;
+use std::sync::mpsc;
+
+thread_local! {
+    /// The current thread's [`ErrorContext`] call stack. Seeded by [`restore_context`]
+    /// on the receiving end of a [`SenderWithContext`], so a panic or fatal error always
+    /// reports the chain of instructions that led to it, even across thread boundaries.
+    pub(crate) static OPENCALLS: RefCell<ErrorContext> = RefCell::new(ErrorContext::new());
+}
+
+/// A snapshot of application state exchanged via [`AppInstruction::GetState`] /
+/// [`AppInstruction::SetState`].
+#[derive(Clone, Default)]
+pub struct AppState;
+
+/// Instructions handled on the app (main) thread.
+#[derive(Clone)]
+pub enum AppInstruction {
+    GetState(mpsc::Sender<AppState>),
+    SetState(AppState),
+    Exit,
+    Error(String),
+}
+
+/// Wraps a raw channel [`mpsc::Sender`] so that every message is bundled with the
+/// sending thread's current [`ErrorContext`]. The receiving end restores that context
+/// into its own [`OPENCALLS`] via [`restore_context`] before dispatching the
+/// instruction, so the call stack survives the hop across the channel (and, eventually,
+/// an IPC socket).
+pub struct SenderWithContext<T> {
+    sender: mpsc::Sender<(T, ErrorContext)>,
+}
+
+impl<T> SenderWithContext<T> {
+    pub fn new(sender: mpsc::Sender<(T, ErrorContext)>) -> Self {
+        Self { sender }
+    }
+
+    pub fn send(&self, event: T) -> Result<(), mpsc::SendError<(T, ErrorContext)>> {
+        let err_ctx = OPENCALLS.with(|ctx| *ctx.borrow());
+        self.sender.send((event, err_ctx))
+    }
+}
+
+impl<T> Clone for SenderWithContext<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// Creates a linked [`SenderWithContext`] / [`mpsc::Receiver`] pair, analogous to
+/// `mpsc::channel`, except the receiver yields the bundled `ErrorContext` alongside
+/// each message.
+pub fn channel_with_context<T>() -> (SenderWithContext<T>, mpsc::Receiver<(T, ErrorContext)>) {
+    let (sender, receiver) = mpsc::channel();
+    (SenderWithContext::new(sender), receiver)
+}
+
+/// Restores `ctx` as this thread's [`OPENCALLS`]. Call this as soon as a message is
+/// pulled off a [`SenderWithContext`]'s receiver, before dispatching it, so the
+/// originating thread's call stack (not just this handler's) is what gets reported if
+/// something downstream panics or returns a fatal error.
+pub fn restore_context(ctx: ErrorContext) {
+    OPENCALLS.with(|opencalls| *opencalls.borrow_mut() = ctx);
+}
+
+/// Spawns a named thread that registers `app_sender` as its [`errors::FatalError`]
+/// sender before running `f`, so `.fatal()` / `.unwrap_or_exit()` calls made on that
+/// thread forward their error back to the app thread as an [`AppInstruction::Error`]
+/// instead of exiting the whole process.
+pub fn spawn_with_fatal_sender<F>(
+    name: &str,
+    app_sender: SenderWithContext<AppInstruction>,
+    f: F,
+) -> std::io::Result<std::thread::JoinHandle<()>>
+where
+    F: FnOnce() + Send + 'static,
+{
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            errors::set_fatal_sender(app_sender);
+            f();
+        })
+}
@@ -0,0 +1,58 @@
+//! Entry point: sets up the app-instruction channel and the threads that report back
+//! to it, then waits for `AppInstruction::Exit` (or a fatal error) to shut down.
+
+mod common;
+mod pty_bus;
+mod screen;
+
+use common::errors::prelude::*;
+use common::{channel_with_context, restore_context, spawn_with_fatal_sender, AppInstruction};
+use common::input::actions::TerminalAction;
+
+fn main() {
+    let (send_app_instructions, receive_app_instructions) = channel_with_context();
+
+    common::errors::install_miette_panic_hook(send_app_instructions.clone());
+
+    let stdin_thread = spawn_with_fatal_sender(
+        "stdin_handler",
+        send_app_instructions.clone(),
+        stdin_loop,
+    )
+    .context("failed to spawn the stdin handler thread")
+    .fatal();
+
+    let pty_thread = spawn_with_fatal_sender("pty", send_app_instructions, || {
+        let _ = pty_bus::spawn_terminal(&TerminalAction::DefaultShell)
+            .context("failed to spawn the default shell")
+            .non_fatal();
+    })
+    .context("failed to spawn the pty thread")
+    .fatal();
+
+    for (instruction, ctx) in receive_app_instructions {
+        restore_context(ctx);
+        match instruction {
+            AppInstruction::Error(report) => {
+                eprintln!("{}", report);
+                std::process::exit(1);
+            }
+            AppInstruction::Exit => break,
+            AppInstruction::GetState(_) | AppInstruction::SetState(_) => {}
+        }
+    }
+
+    let _ = stdin_thread.join();
+    let _ = pty_thread.join();
+}
+
+/// Reads raw input from stdin and forwards it for dispatch. A stand-in for the real
+/// input-reading loop; what matters here is that this thread runs with its own
+/// `FatalError` sender registered via [`spawn_with_fatal_sender`], so a fatal error on
+/// this thread is reported back to the app thread instead of exiting the whole process.
+/// A failed read here is recoverable and is only logged to stderr via `non_fatal`.
+fn stdin_loop() {
+    use std::io::Read;
+    let mut buf = [0u8; 1024];
+    let _ = std::io::stdin().read(&mut buf).context("reading stdin").non_fatal();
+}
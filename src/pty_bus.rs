@@ -0,0 +1,42 @@
+//! The pty thread: spawns and owns the terminal processes backing each pane.
+
+use crate::common::input::actions::TerminalAction;
+use crate::screen::PaneId;
+
+/// An event forwarded from a spawned terminal to the screen thread for rendering.
+pub struct PtyEvent {
+    pub pane_id: PaneId,
+    pub bytes: Vec<u8>,
+}
+
+/// Instructions handled by the pty thread.
+pub enum PtyInstruction {
+    SpawnTerminal(TerminalAction),
+    SpawnTerminalVertically(TerminalAction),
+    SpawnTerminalHorizontally(TerminalAction),
+    ClosePane(PaneId),
+    CloseTab(Vec<PaneId>),
+    NewTab,
+    Quit,
+}
+
+/// Spawns the process described by `action`: the user's login shell for
+/// [`TerminalAction::DefaultShell`], or the given program for
+/// [`TerminalAction::RunCommand`]. The pane's displayed name is derived separately, by
+/// [`crate::screen::Screen::new_pane`] via [`TerminalAction::pane_name`].
+pub fn spawn_terminal(action: &TerminalAction) -> std::io::Result<std::process::Child> {
+    match action {
+        TerminalAction::DefaultShell => {
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+            std::process::Command::new(shell).spawn()
+        }
+        TerminalAction::RunCommand(run_command) => {
+            let mut command = std::process::Command::new(&run_command.command);
+            command.args(&run_command.args);
+            if let Some(cwd) = &run_command.cwd {
+                command.current_dir(cwd);
+            }
+            command.spawn()
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! The screen thread: owns the panes and tabs, and renders them to the terminal.
+
+use crate::common::input::actions::{Direction, SearchDirection, SearchOption, TerminalAction};
+use crate::pty_bus::PtyEvent;
+use std::collections::HashMap;
+
+pub type PaneId = u32;
+
+/// A placeholder for the real tab layout description, applied via
+/// [`ScreenInstruction::ApplyLayout`].
+pub struct Layout;
+
+/// Instructions handled by the screen thread.
+pub enum ScreenInstruction {
+    Pty(PtyEvent),
+    Render,
+    NewPane(Option<Direction>, TerminalAction),
+    HorizontalSplit(PaneId),
+    VerticalSplit(PaneId),
+    WriteCharacter(Vec<u8>),
+    ResizeLeft,
+    ResizeRight,
+    ResizeDown,
+    ResizeUp,
+    MoveFocus,
+    MoveFocusLeft,
+    MoveFocusDown,
+    MoveFocusUp,
+    MoveFocusRight,
+    Quit,
+    ScrollUp,
+    ScrollDown,
+    ClearScroll,
+    CloseFocusedPane,
+    ToggleActiveTerminalFullscreen,
+    SetSelectable(PaneId, bool),
+    SetInvisibleBorders(PaneId, bool),
+    SetMaxHeight(PaneId, usize),
+    ClosePane(PaneId),
+    ApplyLayout(Layout),
+    NewTab(PaneId),
+    SwitchTabNext,
+    SwitchTabPrev,
+    CloseTab,
+    Search(SearchDirection),
+    SearchDown,
+    SearchUp,
+    ResetSearch,
+    SearchToggleOption(SearchOption),
+}
+
+/// A single terminal pane, including the scrollback search state used by
+/// [`ScreenInstruction::Search`] and friends.
+#[derive(Default)]
+pub struct Pane {
+    /// The name shown in the pane's frame, per [`TerminalAction::pane_name`].
+    pub name: Option<String>,
+    pub scrollback: Vec<String>,
+    search_term: Vec<u8>,
+    matches: Vec<usize>,
+    current_match: Option<usize>,
+    case_sensitive: bool,
+    wrap: bool,
+    whole_word: bool,
+}
+
+impl Pane {
+    /// Recomputes `matches` against the current search term and scrollback, then jumps
+    /// to the match in `direction` relative to the current one (or the first/last match
+    /// if there isn't one yet).
+    fn search(&mut self, direction: &SearchDirection) {
+        let needle = String::from_utf8_lossy(&self.search_term).into_owned();
+        self.matches = self
+            .scrollback
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                if needle.is_empty() {
+                    return false;
+                }
+                if self.whole_word {
+                    Self::contains_whole_word(line, &needle, self.case_sensitive)
+                } else if self.case_sensitive {
+                    line.contains(&needle)
+                } else {
+                    line.to_lowercase().contains(&needle.to_lowercase())
+                }
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        if self.matches.is_empty() {
+            self.current_match = None;
+            return;
+        }
+
+        self.current_match = Some(match (self.current_match, direction) {
+            (None, SearchDirection::Down) => 0,
+            (None, SearchDirection::Up) => self.matches.len() - 1,
+            (Some(current), SearchDirection::Down) => {
+                if current + 1 < self.matches.len() {
+                    current + 1
+                } else if self.wrap {
+                    0
+                } else {
+                    current
+                }
+            }
+            (Some(current), SearchDirection::Up) => {
+                if current > 0 {
+                    current - 1
+                } else if self.wrap {
+                    self.matches.len() - 1
+                } else {
+                    0
+                }
+            }
+        });
+    }
+
+    /// The scrollback line the viewport should move to for the current match, if any.
+    pub fn current_match_line(&self) -> Option<usize> {
+        self.current_match.map(|index| self.matches[index])
+    }
+
+    fn reset_search(&mut self) {
+        self.search_term.clear();
+        self.matches.clear();
+        self.current_match = None;
+    }
+
+    fn toggle_option(&mut self, option: &SearchOption) {
+        match option {
+            SearchOption::CaseSensitivity => self.case_sensitive = !self.case_sensitive,
+            SearchOption::Wrap => self.wrap = !self.wrap,
+            SearchOption::WholeWord => self.whole_word = !self.whole_word,
+        }
+    }
+
+    /// Whether `line` contains `needle` as a standalone word, rather than as a substring
+    /// of a longer word (so searching for "cat" matches "a cat sat" but not "catalog").
+    fn contains_whole_word(line: &str, needle: &str, case_sensitive: bool) -> bool {
+        let matches_needle = |word: &str| {
+            if case_sensitive {
+                word == needle
+            } else {
+                word.eq_ignore_ascii_case(needle)
+            }
+        };
+        line.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .any(matches_needle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane_with_scrollback(lines: &[&str]) -> Pane {
+        Pane {
+            scrollback: lines.iter().map(|line| line.to_string()).collect(),
+            ..Pane::default()
+        }
+    }
+
+    #[test]
+    fn whole_word_excludes_substring_matches() {
+        let mut pane = pane_with_scrollback(&["catalog", "a cat sat", "concatenate"]);
+        pane.search_term = b"cat".to_vec();
+        pane.toggle_option(&SearchOption::WholeWord);
+
+        pane.search(&SearchDirection::Down);
+
+        assert_eq!(pane.matches, vec![1]);
+    }
+
+    #[test]
+    fn without_whole_word_substrings_still_match() {
+        let mut pane = pane_with_scrollback(&["catalog", "a cat sat"]);
+        pane.search_term = b"cat".to_vec();
+
+        pane.search(&SearchDirection::Down);
+
+        assert_eq!(pane.matches, vec![0, 1]);
+    }
+}
+
+/// Owns the panes and dispatches [`ScreenInstruction`]s onto them.
+#[derive(Default)]
+pub struct Screen {
+    panes: HashMap<PaneId, Pane>,
+    focused_pane: Option<PaneId>,
+}
+
+impl Screen {
+    /// Creates a new pane running `action`, naming it per
+    /// [`TerminalAction::pane_name`] so a [`TerminalAction::RunCommand`] pane shows a
+    /// useful frame title instead of the default shell's command line.
+    pub fn new_pane(&mut self, id: PaneId, action: &TerminalAction) {
+        self.panes.insert(
+            id,
+            Pane {
+                name: action.pane_name(),
+                ..Pane::default()
+            },
+        );
+        self.focused_pane = Some(id);
+    }
+
+    fn focused_pane_mut(&mut self) -> Option<&mut Pane> {
+        let focused_pane = self.focused_pane?;
+        self.panes.get_mut(&focused_pane)
+    }
+
+    /// Runs [`Pane::search`] on the focused pane, moving its current match in
+    /// `direction`; the scrollback line of that match is then available from
+    /// [`Pane::current_match_line`]. This only updates the pane's search state: it does
+    /// not scroll a viewport or highlight matches. Does nothing if no pane is focused.
+    pub fn search(&mut self, direction: &SearchDirection) {
+        if let Some(pane) = self.focused_pane_mut() {
+            pane.search(direction);
+        }
+    }
+
+    pub fn search_input(&mut self, input: Vec<u8>) {
+        if let Some(pane) = self.focused_pane_mut() {
+            pane.search_term.extend(input);
+        }
+    }
+
+    pub fn reset_search(&mut self) {
+        if let Some(pane) = self.focused_pane_mut() {
+            pane.reset_search();
+        }
+    }
+
+    pub fn search_toggle_option(&mut self, option: &SearchOption) {
+        if let Some(pane) = self.focused_pane_mut() {
+            pane.toggle_option(option);
+        }
+    }
+}